@@ -3,8 +3,11 @@ use comrak::nodes::{AstNode, ListType, NodeValue};
 use comrak::{create_formatter, html, parse_document, Arena, Plugins};
 use lazy_static::lazy_static;
 use regex::Regex;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::io;
 use std::io::{BufWriter, Write};
+use std::rc::Rc;
 
 lazy_static! {
     static ref PLACEHOLDER_REGEX: Regex = Regex::new(r"%(\{|%7B)(\w{1,30})(}|%7D)").unwrap();
@@ -41,7 +44,6 @@ pub struct RenderOptions {
     pub strikethrough: bool,
     pub subscript: bool,
     pub superscript: bool,
-    // pub syntax_highlighting: String,
     pub table: bool,
     pub tagfilter: bool,
     pub tasklist: bool,
@@ -63,6 +65,20 @@ pub struct RenderOptions {
     /// have the format `%{PLACEHOLDER}`
     pub placeholder_detection: bool,
 
+    /// Cap the rendered HTML at this many *visible* characters (i.e. text
+    /// outside of tags, with each HTML entity counting as one character),
+    /// while still emitting well-formed
+    /// markup by closing any tags left open at the cutoff.
+    pub max_output_len: Option<usize>,
+
+    /// Wrap fenced code block tokens in classed `<span>`s instead of
+    /// emitting the raw, unhighlighted source.
+    pub syntax_highlighting: bool,
+
+    /// Assign each heading a unique, URL-safe anchor `id` derived from its
+    /// text content. Takes precedence over `header_ids` when enabled.
+    pub heading_anchors: bool,
+
     pub debug: bool,
 }
 
@@ -70,6 +86,11 @@ pub struct RenderUserData {
     pub default_html: bool,
     pub inapplicable_tasks: bool,
     pub placeholder_detection: bool,
+    pub max_output_len: Option<usize>,
+    pub syntax_highlighting: bool,
+    pub heading_anchors: bool,
+    heading_slugs: Rc<RefCell<IdMap>>,
+    toc_entries: Rc<RefCell<Vec<FlatHeading>>>,
     pub debug: bool,
 }
 
@@ -79,6 +100,11 @@ impl From<&RenderOptions> for RenderUserData {
             default_html: options.default_html,
             inapplicable_tasks: options.inapplicable_tasks,
             placeholder_detection: options.placeholder_detection,
+            max_output_len: options.max_output_len,
+            syntax_highlighting: options.syntax_highlighting,
+            heading_anchors: options.heading_anchors,
+            heading_slugs: Rc::new(RefCell::new(IdMap::default())),
+            toc_entries: Rc::new(RefCell::new(Vec::new())),
             debug: options.debug,
         }
     }
@@ -135,24 +161,333 @@ impl From<&RenderOptions> for comrak::Options<'_> {
 }
 
 pub fn render(text: String, options: RenderOptions) -> String {
+    render_with_plugins(text, options, &comrak::Plugins::default()).0
+}
+
+/// Same as [`render`], but also reports whether the output was cut short by
+/// `RenderOptions::max_output_len`.
+pub fn render_truncatable(text: String, options: RenderOptions) -> (String, bool) {
     render_with_plugins(text, options, &comrak::Plugins::default())
 }
 
-fn render_with_plugins(text: String, render_options: RenderOptions, plugins: &Plugins) -> String {
+/// Renders `text` to HTML the same as [`render`], but also returns a nested
+/// table of contents built from the document's headings. Requires
+/// `RenderOptions::heading_anchors` to collect any entries.
+pub fn render_with_toc(text: String, options: RenderOptions) -> (String, Toc) {
+    let user_data = RenderUserData::from(&options);
+    let toc_entries = user_data.toc_entries.clone();
+    let comrak_options = comrak::Options::from(&options);
+
+    if user_data.default_html {
+        let html = comrak::markdown_to_html_with_plugins(
+            &text,
+            &comrak_options,
+            &Plugins::default(),
+        );
+        return (html, Toc::new());
+    }
+
+    let arena = Arena::new();
+    let root = parse_document(&arena, &text, &comrak_options);
+    let max_output_len = user_data.max_output_len;
+
+    let (html, _truncated) = format_to_string(
+        root,
+        &comrak_options,
+        &Plugins::default(),
+        user_data,
+        max_output_len,
+    );
+
+    let flat: Vec<FlatHeading> = toc_entries.borrow_mut().drain(..).collect();
+    (html, build_toc(flat))
+}
+
+/// Finds every `%{PLACEHOLDER}` occurrence in `text`, the same way `render`
+/// would detect them for `data-placeholder` annotation, but as structured
+/// data with source positions instead of marked-up HTML. Useful for linting
+/// documents for unknown or malformed variables without scraping HTML.
+///
+/// Returns an empty list when `options.placeholder_detection` is off, to
+/// match `render`'s behavior of not annotating placeholders at all in that
+/// case.
+pub fn extract_placeholders(text: String, options: RenderOptions) -> Vec<PlaceholderHit> {
+    if !options.placeholder_detection {
+        return Vec::new();
+    }
+
+    let comrak_options = comrak::Options::from(&options);
+    let arena = Arena::new();
+    let root = parse_document(&arena, &text, &comrak_options);
+
+    let mut hits = Vec::new();
+    walk_for_placeholders(root, false, &mut hits);
+    hits
+}
+
+fn render_with_plugins(
+    text: String,
+    render_options: RenderOptions,
+    plugins: &Plugins,
+) -> (String, bool) {
     let user_data = RenderUserData::from(&render_options);
     let options = comrak::Options::from(&render_options);
 
     if user_data.default_html {
-        return comrak::markdown_to_html_with_plugins(&text, &options, plugins);
+        return (
+            comrak::markdown_to_html_with_plugins(&text, &options, plugins),
+            false,
+        );
     }
 
     let arena = Arena::new();
     let root = parse_document(&arena, &text, &options);
-    let mut bw = BufWriter::new(Vec::new());
+    let max_output_len = user_data.max_output_len;
+
+    format_to_string(root, &options, plugins, user_data, max_output_len)
+}
+
+/// Runs the formatter over `root`, optionally wrapping the output writer in
+/// a [`TagBudgetWriter`] when `max_output_len` is set, and collects the
+/// result into a `String`.
+fn format_to_string<'a>(
+    root: &'a AstNode<'a>,
+    options: &comrak::Options,
+    plugins: &Plugins,
+    user_data: RenderUserData,
+    max_output_len: Option<usize>,
+) -> (String, bool) {
+    let bw = BufWriter::new(Vec::new());
+
+    if let Some(max_output_len) = max_output_len {
+        let mut writer = TagBudgetWriter::new(bw, max_output_len);
 
-    CustomFormatter::format_document_with_plugins(root, &options, &mut bw, plugins, user_data)
+        CustomFormatter::format_document_with_plugins(
+            root, options, &mut writer, plugins, user_data,
+        )
         .unwrap();
-    String::from_utf8(bw.into_inner().unwrap()).unwrap()
+
+        let (bw, truncated) = writer.finish().unwrap();
+        (
+            String::from_utf8(bw.into_inner().unwrap()).unwrap(),
+            truncated,
+        )
+    } else {
+        let mut bw = bw;
+        CustomFormatter::format_document_with_plugins(root, options, &mut bw, plugins, user_data)
+            .unwrap();
+        (String::from_utf8(bw.into_inner().unwrap()).unwrap(), false)
+    }
+}
+
+/// HTML element names that never require a closing tag, and so should
+/// never be pushed onto a [`TagBudgetWriter`]'s open-tag stack.
+const VOID_ELEMENTS: &[&str] = &["img", "input", "br", "hr"];
+
+/// A [`Write`] wrapper that forwards bytes to `inner` until a budget of
+/// *visible* characters has been written, then stops. "Visible" excludes
+/// the bytes making up `<...>` tags and counts an `&...;` HTML entity as a
+/// single unit. While forwarding bytes it tracks which elements are
+/// currently open (ignoring [`VOID_ELEMENTS`]), so that once the budget is
+/// exhausted, [`TagBudgetWriter::finish`] can close them in reverse order
+/// and leave the output well-formed.
+struct TagBudgetWriter<W: Write> {
+    inner: W,
+    max_visible: usize,
+    visible_len: usize,
+    stack: Vec<String>,
+    state: TagBudgetScanState,
+    /// Bytes of a multi-byte UTF-8 character currently being assembled from
+    /// visible text, not yet known to be complete.
+    pending_char: Vec<u8>,
+    truncated: bool,
+    done: bool,
+}
+
+/// The total length in bytes of the UTF-8 sequence starting with lead byte
+/// `lead`, per the standard encoding of the leading byte. Unrecognized lead
+/// bytes are treated as length 1 so scanning can't stall on malformed input.
+fn utf8_seq_len(lead: u8) -> usize {
+    match lead {
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF7 => 4,
+        _ => 1,
+    }
+}
+
+enum TagBudgetScanState {
+    Text,
+    /// Scanning a `<...>` tag. `quote` tracks whether the cursor is inside a
+    /// `"`/`'`-delimited attribute value, so that a `>` inside one does not
+    /// end the tag. Nothing inside a tag counts against the budget.
+    Tag { buf: Vec<u8>, quote: Option<u8> },
+    Entity(Vec<u8>),
+}
+
+impl<W: Write> TagBudgetWriter<W> {
+    fn new(inner: W, max_visible: usize) -> Self {
+        TagBudgetWriter {
+            inner,
+            max_visible,
+            visible_len: 0,
+            stack: Vec::new(),
+            state: TagBudgetScanState::Text,
+            pending_char: Vec::new(),
+            truncated: false,
+            done: false,
+        }
+    }
+
+    /// Consumes the writer, closing any elements still open on the stack
+    /// and returning the inner writer plus whether truncation occurred.
+    fn finish(mut self) -> io::Result<(W, bool)> {
+        // If the stream ended while a tag or entity was still being scanned,
+        // that's genuine end-of-input (e.g. a bare `&` in "Fish & Chips"),
+        // not a budget cutoff — flush the buffered bytes verbatim rather
+        // than silently dropping them. A budget cutoff only ever happens in
+        // text or at the end of an entity, so nothing is buffered then.
+        if !self.truncated {
+            match std::mem::replace(&mut self.state, TagBudgetScanState::Text) {
+                TagBudgetScanState::Text => {}
+                TagBudgetScanState::Tag { buf, .. } => self.inner.write_all(&buf)?,
+                TagBudgetScanState::Entity(buf) => self.inner.write_all(&buf)?,
+            }
+            if !self.pending_char.is_empty() {
+                self.inner.write_all(&self.pending_char)?;
+            }
+        }
+        if self.truncated {
+            self.inner.write_all("…".as_bytes())?;
+        }
+        while let Some(name) = self.stack.pop() {
+            write!(self.inner, "</{}>", name)?;
+        }
+        Ok((self.inner, self.truncated))
+    }
+
+    fn count_visible(&mut self, len: usize) {
+        self.visible_len += len;
+        if self.visible_len >= self.max_visible {
+            self.done = true;
+            self.truncated = true;
+        }
+    }
+
+    /// Buffers `byte` as part of the UTF-8 character currently being
+    /// assembled from visible content, returning its complete bytes once a
+    /// full character has been collected (`None` while still incomplete).
+    /// Counting and emitting whole characters - rather than raw bytes one at
+    /// a time - keeps a budget cutoff from ever landing mid-codepoint, which
+    /// would otherwise hand back invalid UTF-8.
+    fn push_visible_byte(&mut self, byte: u8) -> Option<Vec<u8>> {
+        self.pending_char.push(byte);
+        if self.pending_char.len() >= utf8_seq_len(self.pending_char[0]) {
+            Some(std::mem::take(&mut self.pending_char))
+        } else {
+            None
+        }
+    }
+
+    fn handle_tag(&mut self, tag: &[u8]) -> io::Result<()> {
+        let text = String::from_utf8_lossy(tag);
+        let inner = text
+            .strip_prefix('<')
+            .and_then(|t| t.strip_suffix('>'))
+            .unwrap_or(&text);
+        let closing = inner.starts_with('/');
+        let self_closing = inner.trim_end().ends_with('/');
+        let name = inner
+            .trim_start_matches('/')
+            .trim_end_matches('/')
+            .split(|c: char| c.is_whitespace())
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+
+        if !name.is_empty() && !VOID_ELEMENTS.contains(&name.as_str()) {
+            if closing {
+                if let Some(pos) = self.stack.iter().rposition(|open| open == &name) {
+                    self.stack.truncate(pos);
+                }
+            } else if !self_closing {
+                self.stack.push(name);
+            }
+        }
+
+        self.inner.write_all(tag)
+    }
+}
+
+impl<W: Write> Write for TagBudgetWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            if self.done {
+                continue;
+            }
+
+            // Take the state out by value so the arms below can freely call
+            // other `&mut self` methods (e.g. `count_visible`, `handle_tag`)
+            // without fighting the borrow checker over `self.state`.
+            self.state = match std::mem::replace(&mut self.state, TagBudgetScanState::Text) {
+                TagBudgetScanState::Text => {
+                    if byte == b'<' {
+                        TagBudgetScanState::Tag {
+                            buf: vec![byte],
+                            quote: None,
+                        }
+                    } else if byte == b'&' {
+                        TagBudgetScanState::Entity(vec![byte])
+                    } else {
+                        if let Some(ch) = self.push_visible_byte(byte) {
+                            self.inner.write_all(&ch)?;
+                            self.count_visible(1);
+                        }
+                        TagBudgetScanState::Text
+                    }
+                }
+                TagBudgetScanState::Tag { mut buf, quote } => {
+                    buf.push(byte);
+
+                    let next_quote = match quote {
+                        Some(q) if byte == q => None,
+                        Some(q) => Some(q),
+                        None if byte == b'"' || byte == b'\'' => Some(byte),
+                        None => None,
+                    };
+
+                    if byte == b'>' && next_quote.is_none() {
+                        self.handle_tag(&buf)?;
+                        TagBudgetScanState::Text
+                    } else {
+                        TagBudgetScanState::Tag {
+                            buf,
+                            quote: next_quote,
+                        }
+                    }
+                }
+                TagBudgetScanState::Entity(mut entity) => {
+                    entity.push(byte);
+                    // Bail out of entity-scanning if it runs suspiciously
+                    // long: the buffered bytes are written out as they are
+                    // and, like an entity, count as one visible unit.
+                    if byte == b';' || entity.len() > 32 {
+                        self.inner.write_all(&entity)?;
+                        self.count_visible(1);
+                        TagBudgetScanState::Text
+                    } else {
+                        TagBudgetScanState::Entity(entity)
+                    }
+                }
+            };
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
 }
 
 // The important thing to remember is that this overrides the default behavior of the
@@ -177,6 +512,12 @@ create_formatter!(CustomFormatter<RenderUserData>, {
     },
     NodeValue::TaskItem(_) => |context, node, entering| {
         return render_task_item(context, node, entering);
+    },
+    NodeValue::CodeBlock(_) => |context, node, entering| {
+        return render_code_block(context, node, entering);
+    },
+    NodeValue::Heading(_) => |context, node, entering| {
+        return render_heading(context, node, entering);
     }
 });
 
@@ -393,6 +734,395 @@ fn render_task_item<'a>(
 
     Ok(ChildRendering::HTML)
 }
+
+// Overridden to wrap tokens in classed spans when `syntax_highlighting` is
+// enabled. Falls back to the default `<pre><code>` rendering whenever there's
+// no language info string to highlight against.
+fn render_code_block<'a>(
+    context: &mut Context<RenderUserData>,
+    node: &'a AstNode<'a>,
+    entering: bool,
+) -> io::Result<ChildRendering> {
+    let NodeValue::CodeBlock(ref ncb) = node.data.borrow().value else {
+        panic!("Attempt to render invalid node as code block")
+    };
+
+    let first_tag = ncb.info.split(' ').next().unwrap_or("");
+
+    if !context.user.syntax_highlighting || first_tag.is_empty() {
+        return html::format_node_default(context, node, entering);
+    }
+
+    if !entering {
+        return Ok(ChildRendering::HTML);
+    }
+
+    let lang = if context.options.render.full_info_string {
+        ncb.info.as_str()
+    } else {
+        first_tag
+    };
+
+    context.cr()?;
+    context.write_all(b"<pre")?;
+    if context.options.render.github_pre_lang {
+        context.write_all(b" lang=\"")?;
+        context.escape(lang.as_bytes())?;
+        context.write_all(b"\"")?;
+        html::render_sourcepos(context, node)?;
+        context.write_all(b"><code>")?;
+    } else {
+        html::render_sourcepos(context, node)?;
+        context.write_all(b"><code class=\"language-")?;
+        context.escape(lang.as_bytes())?;
+        context.write_all(b"\">")?;
+    }
+
+    write_highlighted(context, &ncb.literal)?;
+
+    context.write_all(b"</code></pre>\n")?;
+
+    Ok(ChildRendering::HTML)
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum HighlightClass {
+    Keyword,
+    Identifier,
+    String,
+    Number,
+    Comment,
+    Punctuation,
+    Whitespace,
+}
+
+impl HighlightClass {
+    fn span_class(self) -> Option<&'static str> {
+        match self {
+            HighlightClass::Keyword => Some("kw"),
+            HighlightClass::Identifier => Some("ident"),
+            HighlightClass::String => Some("string"),
+            HighlightClass::Number => Some("number"),
+            HighlightClass::Comment => Some("comment"),
+            HighlightClass::Punctuation => Some("punct"),
+            HighlightClass::Whitespace => None,
+        }
+    }
+}
+
+// A small, language-agnostic set of keywords shared by most C-like,
+// Python-like and Ruby-like languages GitLab renders code blocks for.
+// This is a best-effort classification, not a real per-language lexer.
+const HIGHLIGHT_KEYWORDS: &[&str] = &[
+    "if", "else", "elif", "unless", "for", "while", "do", "switch", "case", "break",
+    "continue", "return", "yield", "function", "fn", "def", "class", "struct", "enum",
+    "impl", "trait", "interface", "module", "mod", "import", "from", "export", "use",
+    "pub", "priv", "private", "public", "protected", "static", "const", "let", "var",
+    "mut", "new", "delete", "try", "catch", "finally", "throw", "raise", "except",
+    "async", "await", "match", "type", "typeof", "instanceof", "in", "of", "as", "is",
+    "not", "and", "or", "null", "nil", "None", "true", "false", "True", "False",
+    "self", "this", "super", "extends", "implements", "package", "namespace", "void",
+    "int", "float", "double", "bool", "boolean", "string", "char", "byte", "long", "short",
+];
+
+// Advances `chars` past a run of characters matching `pred`, starting from
+// whatever `chars` is currently positioned at, and returns the byte offset
+// just past the last character consumed. Callers only invoke this when the
+// character under the cursor already satisfies `pred`.
+fn consume_while(
+    chars: &mut std::iter::Peekable<std::str::CharIndices>,
+    mut pred: impl FnMut(char) -> bool,
+) -> usize {
+    let mut end = 0;
+    while let Some(&(i, c)) = chars.peek() {
+        if !pred(c) {
+            break;
+        }
+        end = i + c.len_utf8();
+        chars.next();
+    }
+    end
+}
+
+// Tokenizes `src` into contiguous `(class, byte_range)` runs. Intentionally
+// simple: it classifies by shape (quotes, digits, word characters, line/block
+// comment markers) rather than understanding any particular language's
+// grammar. Walks `char_indices()` rather than raw bytes so multi-byte UTF-8
+// sequences (accented names, CJK, emoji, ...) never get sliced mid-character.
+fn tokenize_for_highlight(src: &str) -> Vec<(HighlightClass, std::ops::Range<usize>)> {
+    let mut tokens = Vec::new();
+    let mut chars = src.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            let end = consume_while(&mut chars, |c| c.is_whitespace());
+            tokens.push((HighlightClass::Whitespace, start..end));
+        } else if c == '#' || src[start..].starts_with("//") {
+            let end = consume_while(&mut chars, |c| c != '\n');
+            tokens.push((HighlightClass::Comment, start..end));
+        } else if src[start..].starts_with("/*") {
+            chars.next();
+            chars.next();
+            let mut end = start + 2;
+            loop {
+                match chars.peek().copied() {
+                    None => break,
+                    Some((i, _)) if src[i..].starts_with("*/") => {
+                        chars.next();
+                        chars.next();
+                        end = i + 2;
+                        break;
+                    }
+                    Some((i, c)) => {
+                        end = i + c.len_utf8();
+                        chars.next();
+                    }
+                }
+            }
+            tokens.push((HighlightClass::Comment, start..end));
+        } else if c == '"' || c == '\'' {
+            let quote = c;
+            chars.next();
+            let mut end = start + c.len_utf8();
+            while let Some((i, ch)) = chars.next() {
+                end = i + ch.len_utf8();
+                if ch == '\\' {
+                    if let Some((i2, ch2)) = chars.next() {
+                        end = i2 + ch2.len_utf8();
+                    }
+                    continue;
+                }
+                if ch == quote {
+                    break;
+                }
+            }
+            tokens.push((HighlightClass::String, start..end));
+        } else if c.is_ascii_digit() {
+            let end =
+                consume_while(&mut chars, |c| c.is_ascii_alphanumeric() || c == '.' || c == '_');
+            tokens.push((HighlightClass::Number, start..end));
+        } else if c.is_alphabetic() || c == '_' {
+            let end = consume_while(&mut chars, |c| c.is_alphanumeric() || c == '_');
+            let class = if HIGHLIGHT_KEYWORDS.contains(&&src[start..end]) {
+                HighlightClass::Keyword
+            } else {
+                HighlightClass::Identifier
+            };
+            tokens.push((class, start..end));
+        } else {
+            chars.next();
+            tokens.push((HighlightClass::Punctuation, start..start + c.len_utf8()));
+        }
+    }
+
+    tokens
+}
+
+// Writes `literal` as a run of classed spans, merging consecutive tokens of
+// the same class so plain punctuation/identifier runs don't get split into
+// one `<span>` per character.
+fn write_highlighted(context: &mut Context<RenderUserData>, literal: &str) -> io::Result<()> {
+    let tokens = tokenize_for_highlight(literal);
+    let mut idx = 0;
+
+    while idx < tokens.len() {
+        let (class, range) = &tokens[idx];
+        let start = range.start;
+        let mut end = range.end;
+        let mut next = idx + 1;
+
+        while next < tokens.len() && tokens[next].0 == *class {
+            end = tokens[next].1.end;
+            next += 1;
+        }
+
+        let text = &literal[start..end];
+        match class.span_class() {
+            Some(span_class) => {
+                write!(context, "<span class=\"{}\">", span_class)?;
+                context.escape(text.as_bytes())?;
+                context.write_all(b"</span>")?;
+            }
+            None => {
+                context.escape(text.as_bytes())?;
+            }
+        }
+
+        idx = next;
+    }
+
+    Ok(())
+}
+
+// Overridden to assign each heading a unique `id` derived from its text
+// content, and to record a flat (level, id, text) entry per heading so
+// `render_with_toc` can assemble a nested table of contents afterwards.
+fn render_heading<'a>(
+    context: &mut Context<RenderUserData>,
+    node: &'a AstNode<'a>,
+    entering: bool,
+) -> io::Result<ChildRendering> {
+    if !context.user.heading_anchors {
+        return html::format_node_default(context, node, entering);
+    }
+
+    let NodeValue::Heading(ref nch) = node.data.borrow().value else {
+        panic!("Attempt to render invalid node as heading")
+    };
+    let level = nch.level;
+
+    if entering {
+        let text = collect_heading_text(node);
+        let id = context.user.heading_slugs.borrow_mut().ensure_unique(&text);
+
+        context.user.toc_entries.borrow_mut().push(FlatHeading {
+            level,
+            id: id.clone(),
+            text,
+        });
+
+        context.cr()?;
+        write!(context, "<h{} id=\"{}\"", level, id)?;
+        html::render_sourcepos(context, node)?;
+        context.write_all(b">")?;
+    } else {
+        writeln!(context, "</h{}>", level)?;
+    }
+
+    Ok(ChildRendering::HTML)
+}
+
+/// Walks a heading's child subtree concatenating `Text` and `Code` literals,
+/// turning `SoftBreak`/`LineBreak` into spaces, to produce the plain text a
+/// slug should be derived from.
+fn collect_heading_text<'a>(node: &'a AstNode<'a>) -> String {
+    let mut text = String::new();
+
+    for child in node.children() {
+        match &child.data.borrow().value {
+            NodeValue::Text(literal) => text.push_str(literal),
+            NodeValue::Code(code) => text.push_str(&code.literal),
+            NodeValue::SoftBreak | NodeValue::LineBreak => text.push(' '),
+            _ => text.push_str(&collect_heading_text(child)),
+        }
+    }
+
+    text
+}
+
+/// Assigns URL-safe anchor ids, deduplicating repeats by appending `-1`,
+/// `-2`, … so every id handed out is unique.
+#[derive(Debug, Clone, Default)]
+pub struct IdMap {
+    seen: HashMap<String, usize>,
+}
+
+impl IdMap {
+    pub fn new() -> Self {
+        IdMap::default()
+    }
+
+    /// Slugifies `text` and returns a unique id, recording it so future
+    /// collisions get a numeric suffix.
+    pub fn ensure_unique(&mut self, text: &str) -> String {
+        let base = slugify(text);
+        let mut count = self.seen.get(&base).copied().unwrap_or(0);
+        let mut id = if count == 0 {
+            base.clone()
+        } else {
+            format!("{}-{}", base, count)
+        };
+        // A suffixed id may already have been handed out as another
+        // heading's own slug (e.g. "Foo 1" -> "foo-1"), so keep counting.
+        while count > 0 && self.seen.contains_key(&id) {
+            count += 1;
+            id = format!("{}-{}", base, count);
+        }
+        self.seen.insert(base, count + 1);
+        self.seen.entry(id.clone()).or_insert(1);
+        id
+    }
+}
+
+/// Lowercases `text`, turns runs of non-alphanumeric characters into a
+/// single hyphen, and trims leading/trailing hyphens.
+fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    let mut last_was_hyphen = true;
+
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            for lower in c.to_lowercase() {
+                slug.push(lower);
+            }
+            last_was_hyphen = false;
+        } else if !last_was_hyphen {
+            slug.push('-');
+            last_was_hyphen = true;
+        }
+    }
+
+    while slug.ends_with('-') {
+        slug.pop();
+    }
+
+    slug
+}
+
+/// A heading as encountered in document order, before being nested into a
+/// [`Toc`] tree.
+struct FlatHeading {
+    level: u8,
+    id: String,
+    text: String,
+}
+
+/// One entry of a [`render_with_toc`] table of contents: a heading plus any
+/// headings nested beneath it by level.
+#[derive(Debug, Clone)]
+pub struct TocEntry {
+    pub level: u8,
+    pub id: String,
+    pub text: String,
+    pub children: Vec<TocEntry>,
+}
+
+pub type Toc = Vec<TocEntry>;
+
+/// Nests a flat, document-order list of headings into a [`Toc`] tree by
+/// heading level.
+fn build_toc(flat: Vec<FlatHeading>) -> Toc {
+    let mut root: Toc = Vec::new();
+    let mut stack: Vec<TocEntry> = Vec::new();
+
+    fn attach(root: &mut Toc, stack: &mut [TocEntry], entry: TocEntry) {
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(entry),
+            None => root.push(entry),
+        }
+    }
+
+    for heading in flat {
+        while stack.last().is_some_and(|top| top.level >= heading.level) {
+            let finished = stack.pop().unwrap();
+            attach(&mut root, &mut stack, finished);
+        }
+
+        stack.push(TocEntry {
+            level: heading.level,
+            id: heading.id,
+            text: heading.text,
+            children: Vec::new(),
+        });
+    }
+
+    while let Some(finished) = stack.pop() {
+        attach(&mut root, &mut stack, finished);
+    }
+
+    root
+}
+
 fn render_text<'a>(
     context: &mut Context<RenderUserData>,
     node: &'a AstNode<'a>,
@@ -441,3 +1171,153 @@ fn render_text<'a>(
 
     Ok(ChildRendering::HTML)
 }
+
+/// Which part of the document a detected placeholder was found in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaceholderContainer {
+    /// Plain body text, or the visible text of a link.
+    Text,
+    LinkUrl,
+    ImageUrl,
+    ImageAlt,
+}
+
+/// A 1-indexed `line, column` source range, read the same way
+/// `html::render_sourcepos` reads `node.data.borrow().sourcepos`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourcePosition {
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlaceholderHit {
+    pub name: String,
+    pub container: PlaceholderContainer,
+    pub position: SourcePosition,
+
+    /// `true` when this placeholder was reconstructed from multiple
+    /// adjacent text nodes rather than matched within a single one. This is
+    /// the current limitation where an underscore in link/image text (e.g.
+    /// `[%{a_b}](url)`) stops `comrak` from merging the surrounding text
+    /// nodes, so `render`'s placeholder detection would *not* actually
+    /// annotate it with `data-placeholder`.
+    pub split: bool,
+}
+
+fn sourcepos_of<'a>(node: &'a AstNode<'a>) -> SourcePosition {
+    let sourcepos = node.data.borrow().sourcepos;
+    SourcePosition {
+        start_line: sourcepos.start.line,
+        start_column: sourcepos.start.column,
+        end_line: sourcepos.end.line,
+        end_column: sourcepos.end.column,
+    }
+}
+
+fn push_placeholder_matches(
+    haystack: &str,
+    container: PlaceholderContainer,
+    position: SourcePosition,
+    hits: &mut Vec<PlaceholderHit>,
+) {
+    for cap in PLACEHOLDER_REGEX.captures_iter(haystack) {
+        hits.push(PlaceholderHit {
+            name: cap[2].to_string(),
+            container,
+            position,
+            split: false,
+        });
+    }
+}
+
+fn walk_for_placeholders<'a>(node: &'a AstNode<'a>, in_image: bool, hits: &mut Vec<PlaceholderHit>) {
+    let mut child_in_image = in_image;
+
+    match &node.data.borrow().value {
+        NodeValue::Link(nl) => {
+            push_placeholder_matches(&nl.url, PlaceholderContainer::LinkUrl, sourcepos_of(node), hits);
+            collect_split_placeholders(node, false, hits);
+        }
+        NodeValue::Image(nl) => {
+            push_placeholder_matches(&nl.url, PlaceholderContainer::ImageUrl, sourcepos_of(node), hits);
+            collect_split_placeholders(node, true, hits);
+            child_in_image = true;
+        }
+        NodeValue::Text(literal) => {
+            let container = if in_image {
+                PlaceholderContainer::ImageAlt
+            } else {
+                PlaceholderContainer::Text
+            };
+            push_placeholder_matches(literal, container, sourcepos_of(node), hits);
+        }
+        _ => {}
+    }
+
+    for child in node.children() {
+        walk_for_placeholders(child, child_in_image, hits);
+    }
+}
+
+/// Re-scans a link/image's text as one concatenated string to catch
+/// placeholders split across adjacent text nodes (see [`PlaceholderHit::split`]).
+/// Matches found fully within a single text node are skipped here, since the
+/// per-node pass in [`walk_for_placeholders`] already reports those.
+fn collect_split_placeholders<'a>(node: &'a AstNode<'a>, is_image: bool, hits: &mut Vec<PlaceholderHit>) {
+    let mut concatenated = String::new();
+    let mut segments: Vec<(usize, usize)> = Vec::new();
+    collect_text_segments(node, &mut concatenated, &mut segments);
+
+    let container = if is_image {
+        PlaceholderContainer::ImageAlt
+    } else {
+        PlaceholderContainer::Text
+    };
+    let position = sourcepos_of(node);
+
+    for cap in PLACEHOLDER_REGEX.captures_iter(&concatenated) {
+        let whole = cap.get(0).unwrap();
+        let fully_contained = segments
+            .iter()
+            .any(|&(start, end)| start <= whole.start() && whole.end() <= end);
+
+        if !fully_contained {
+            hits.push(PlaceholderHit {
+                name: cap[2].to_string(),
+                container,
+                position,
+                split: true,
+            });
+        }
+    }
+}
+
+/// Concatenates a node's descendant `Text` literals in document order,
+/// recording the `(start, end)` byte range each literal occupies in the
+/// concatenation so [`collect_split_placeholders`] can tell whether a match
+/// crossed a node boundary.
+fn collect_text_segments<'a>(
+    node: &'a AstNode<'a>,
+    out: &mut String,
+    segments: &mut Vec<(usize, usize)>,
+) {
+    for child in node.children() {
+        match &child.data.borrow().value {
+            NodeValue::Text(literal) => {
+                let start = out.len();
+                out.push_str(literal);
+                segments.push((start, out.len()));
+            }
+            NodeValue::SoftBreak | NodeValue::LineBreak => out.push(' '),
+            // A nested link/image is handled by its own `walk_for_placeholders`
+            // call (and its own `collect_split_placeholders` scan), so don't
+            // recurse into one here - doing so would concatenate its text into
+            // this node's scan too and double-report the same placeholder.
+            NodeValue::Link(_) | NodeValue::Image(_) => {}
+            _ => collect_text_segments(child, out, segments),
+        }
+    }
+}